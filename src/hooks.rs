@@ -0,0 +1,90 @@
+//! External hook scripts: on notable events (hub notifications, writes
+//! sent, errors) spawn a user-configured command, passing context through
+//! environment variables so scripts don't need to know anything about this
+//! crate.
+
+use std::process::Command;
+use std::thread;
+
+use crate::proto::CfgRead;
+
+/// Hook scripts configured via `--on-notification`, `--on-error`, and
+/// `--on-write-sent`. Any of them may be unset, in which case the
+/// corresponding event is a no-op. They fire in the interactive console and
+/// the one-shot `read`/`write` commands; `serve` does not run them.
+#[derive(Clone, Default)]
+pub struct Hooks {
+    pub on_notification: Option<String>,
+    pub on_error: Option<String>,
+    pub on_write_sent: Option<String>,
+}
+
+fn hex_frame(frame: &[u8]) -> String {
+    frame
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join("")
+}
+
+/// Spawns `script` with the given context as environment variables.
+/// Failures to spawn are logged to stderr rather than propagated, since a
+/// broken hook shouldn't take the console down with it. The child is
+/// reaped on a background thread so a long REPL session firing the same
+/// hook repeatedly doesn't accumulate zombie processes.
+fn spawn(script: &str, vars: &[(&str, String)]) {
+    let mut command = Command::new(script);
+    for (key, value) in vars {
+        command.env(key, value);
+    }
+
+    match command.spawn() {
+        Ok(mut child) => {
+            thread::spawn(move || {
+                let _ = child.wait();
+            });
+        }
+        Err(e) => eprintln!("hook {} failed to start: {}", script, e),
+    }
+}
+
+impl Hooks {
+    /// Fires `--on-notification` for an unsolicited parameter-changed event.
+    pub fn on_notification(&self, notification: &CfgRead, frame: &[u8]) {
+        if let Some(script) = &self.on_notification {
+            spawn(
+                script,
+                &[
+                    ("ARCANE_NODE_ID", notification.node_id.to_string()),
+                    ("ARCANE_PARAM_INDEX", notification.param_index.to_string()),
+                    ("ARCANE_VALUE", notification.value.to_string()),
+                    ("ARCANE_FRAME", hex_frame(frame)),
+                ],
+            );
+        }
+    }
+
+    /// Fires `--on-write-sent` once a write has been handed off to the
+    /// serial port. The wire protocol has no write-acknowledgement frame,
+    /// so this does not mean the hub has received or applied it.
+    pub fn on_write_sent(&self, node_id: u8, param_index: u8, value: u64, frame: &[u8]) {
+        if let Some(script) = &self.on_write_sent {
+            spawn(
+                script,
+                &[
+                    ("ARCANE_NODE_ID", node_id.to_string()),
+                    ("ARCANE_PARAM_INDEX", param_index.to_string()),
+                    ("ARCANE_VALUE", value.to_string()),
+                    ("ARCANE_FRAME", hex_frame(frame)),
+                ],
+            );
+        }
+    }
+
+    /// Fires `--on-error` whenever a command or response fails.
+    pub fn on_error(&self, message: &str) {
+        if let Some(script) = &self.on_error {
+            spawn(script, &[("ARCANE_ERROR", message.to_string())]);
+        }
+    }
+}
@@ -1,79 +1,359 @@
-use std::{
-    io::{self, Write},
-    process::exit,
-    thread,
-    time::Duration,
-};
+use std::{io::Write, process::exit, sync::mpsc, thread, time::Duration};
 
 use console::{style, Term};
 
 use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use rustyline::error::ReadlineError;
+use rustyline::{Editor, ExternalPrinter};
+
+mod hooks;
+mod output;
+mod proto;
+mod repl;
+mod server;
+
+use hooks::Hooks;
+use output::OutputFormat;
+
+/// Where command history is persisted between sessions.
+const HISTORY_FILE: &str = ".arcane_history";
+
+/// Default baud rate for the serial connection.
+const DEFAULT_BAUD: u32 = 115_200;
+
+/// Console for the ARCANE hub: run interactively by default, or pass a
+/// one-shot command / `serve` to drive it non-interactively.
+#[derive(Parser)]
+#[command(name = "arcane", about = "Console for the ARCANE hub")]
+struct Cli {
+    /// Serial port to use, e.g. /dev/ttyUSB0. Auto-detected by USB product
+    /// string ("ARCANE Hub") if omitted.
+    #[arg(long, global = true)]
+    port: Option<String>,
+
+    /// Baud rate for the serial connection.
+    #[arg(long, default_value_t = DEFAULT_BAUD, global = true)]
+    baud: u32,
+
+    /// List available serial ports and exit.
+    #[arg(long)]
+    list_ports: bool,
+
+    /// Output format for command results and notifications.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Human, global = true)]
+    format: OutputFormat,
+
+    /// Script to run whenever the hub sends an unsolicited notification.
+    #[arg(long, global = true)]
+    on_notification: Option<String>,
+
+    /// Script to run whenever a command or response fails.
+    #[arg(long, global = true)]
+    on_error: Option<String>,
+
+    /// Script to run after a write has been sent to the hub. Note this
+    /// fires once the bytes are handed to the serial port, not once the
+    /// hub has acknowledged them -- the wire protocol has no write-ack
+    /// frame.
+    #[arg(long, global = true)]
+    on_write_sent: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+impl Cli {
+    fn hooks(&self) -> Hooks {
+        Hooks {
+            on_notification: self.on_notification.clone(),
+            on_error: self.on_error.clone(),
+            on_write_sent: self.on_write_sent.clone(),
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Send a single write command and exit.
+    Write {
+        node_id: u8,
+        param_index: u8,
+        param_value: u64,
+    },
+    /// Send a single read command and exit.
+    Read { node_id: u8, param_index: u8 },
+    /// Run the headless TCP bridge instead of the interactive console.
+    Serve {
+        /// Address to listen on.
+        #[arg(long, default_value = "127.0.0.1:7000")]
+        listen: String,
+    },
+}
+
+/// Returns the styled prompt string shown by the REPL.
+fn prompt() -> String {
+    format!("{} ", style("▶").cyan())
+}
 
 fn main() -> Result<()> {
-    let term = Term::stdout();
+    let cli = Cli::parse();
 
-    println!("parsed command: {:#04x?}", parse_write("write 1 2 567")?);
+    if cli.list_ports {
+        return list_ports();
+    }
 
-    let ports = serialport::available_ports().context("No ports found!")?;
+    match cli.command {
+        Some(Command::Serve { listen }) => {
+            let port = open_hub_port(cli.port.as_deref(), cli.baud)?;
+            return server::serve(&listen, port);
+        }
+        Some(Command::Write {
+            node_id,
+            param_index,
+            param_value,
+        }) => {
+            return run_one_shot_write(
+                cli.port.as_deref(),
+                cli.baud,
+                cli.format,
+                &cli.hooks(),
+                node_id,
+                param_index,
+                param_value,
+            );
+        }
+        Some(Command::Read {
+            node_id,
+            param_index,
+        }) => {
+            return run_one_shot_read(
+                cli.port.as_deref(),
+                cli.baud,
+                cli.format,
+                &cli.hooks(),
+                node_id,
+                param_index,
+            );
+        }
+        None => {}
+    }
 
-    let arcane_port = ports
-        .iter()
-        .find(|p| match &p.port_type {
-            serialport::SerialPortType::UsbPort(usb_info) => {
-                usb_info.product.as_deref() == Some("ARCANE Hub")
-            }
-            _ => false,
-        })
-        .context("No hub found")?;
+    let hooks = cli.hooks();
+    let term = Term::stdout();
 
-    let mut port = serialport::new(&arcane_port.port_name, 115_200)
-        .timeout(Duration::from_millis(10))
-        .open()
-        .context("Failed to open serial port")?;
+    let mut port = open_hub_port(cli.port.as_deref(), cli.baud)?;
+
+    let reader_port = port
+        .try_clone()
+        .context("Failed to clone serial port for background reader")?;
+
+    let (response_tx, response_rx) = mpsc::channel::<Vec<u8>>();
+    let (notify_tx, notify_rx) = mpsc::channel::<(proto::CfgRead, Vec<u8>)>();
 
     // term.clear_screen()?;
     term.set_title("ARCANE CLI");
     println!("{}", style("Welcome to the ARCANE console!").bold().cyan());
     println!("enter \"help\" to see your options\n");
 
+    let mut rl = Editor::<repl::CmdHelper, rustyline::history::FileHistory>::new()
+        .context("Failed to start the REPL")?;
+    rl.set_helper(Some(repl::CmdHelper::new()));
+    let _ = rl.load_history(HISTORY_FILE);
+
+    // Routes notification output through rustyline's own render state instead
+    // of manual escape codes, so a notification arriving mid-command doesn't
+    // leave rustyline's idea of what's on screen out of sync with reality.
+    let mut printer = rl
+        .create_external_printer()
+        .context("Failed to create REPL printer")?;
+
+    let format = cli.format;
+    let notify_hooks = hooks.clone();
+    thread::spawn(move || proto::demux_frames(reader_port, response_tx, notify_tx));
+    thread::spawn(move || {
+        for (notification, frame) in notify_rx {
+            let line = output::format_notification(format, &notification);
+            let _ = printer.print(format!("{}\n", line));
+            notify_hooks.on_notification(&notification, &frame);
+        }
+    });
+
     loop {
-        println!("Please enter an ARCANE configuration command");
+        let readline = rl.readline(&prompt());
 
-        print!("▶ ");
-        io::stdout().flush()?;
+        let command = match readline {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => {
+                println!("Goodbye!");
+                break;
+            }
+            Err(e) => return Err(e).context("Failed to read command"),
+        };
 
-        let mut command = String::new();
-        io::stdin()
-            .read_line(&mut command)
-            .context("Can't read from stdin")?;
+        let _ = rl.add_history_entry(command.as_str());
 
-        match command.as_str().trim_end() {
+        match command.trim() {
+            "" => continue,
+            "quit" => {
+                println!("Goodbye!");
+                break;
+            }
             "help" => {
                 println!("\navailable commands:\n");
                 println!("write <node_id> <param_index> <param_value>");
-                println!("read  <node_id> <param_index> <param_value>");
+                println!("read  <node_id> <param_index>");
             }
-            cmd if cmd.starts_with("read") => println!("read not implemented yet!"),
+            cmd if cmd.starts_with("read") => match parse_read(&command) {
+                Ok(data) => {
+                    if let Err(e) = port.write(&data).context("Write failed!") {
+                        hooks.on_error(&e.to_string());
+                        return Err(e);
+                    }
+                    match proto::recv_matching_response(
+                        &response_rx,
+                        data[1],
+                        data[2],
+                        Duration::from_millis(200),
+                    ) {
+                        Ok(reply) => output::print_read(cli.format, &reply, &data),
+                        Err(e) => {
+                            output::print_error(cli.format, &e.to_string());
+                            hooks.on_error(&e.to_string());
+                        }
+                    }
+                }
+                Err(e) => {
+                    output::print_error(cli.format, &e.to_string());
+                    hooks.on_error(&e.to_string());
+                }
+            },
             cmd if cmd.starts_with("write") => match parse_write(&command) {
                 Ok(data) => {
-                    port.write(&data).context("Write failed!")?;
+                    if let Err(e) = port.write(&data).context("Write failed!") {
+                        hooks.on_error(&e.to_string());
+                        return Err(e);
+                    }
+                    let parts: Vec<&str> = command.split_whitespace().collect();
+                    let value: u64 = parts[3].parse().unwrap_or_default();
+                    output::print_write(cli.format, data[1], data[2], value, &data);
+                    hooks.on_write_sent(data[1], data[2], value, &data);
+                }
+                Err(e) => {
+                    output::print_error(cli.format, &e.to_string());
+                    hooks.on_error(&e.to_string());
                 }
-                Err(e) => println!("Error: {}", e),
             },
             _ => println!("unknown command..."),
         }
+    }
+
+    rl.save_history(HISTORY_FILE)
+        .context("Failed to save command history")?;
+
+    Ok(())
+}
+
+/// Opens the given serial port, or auto-detects the ARCANE hub by USB
+/// product string if none was given on the command line.
+fn open_hub_port(port_name: Option<&str>, baud: u32) -> Result<Box<dyn serialport::SerialPort>> {
+    let port_name = match port_name {
+        Some(name) => name.to_string(),
+        None => {
+            let ports = serialport::available_ports().context("No ports found!")?;
+
+            ports
+                .iter()
+                .find(|p| match &p.port_type {
+                    serialport::SerialPortType::UsbPort(usb_info) => {
+                        usb_info.product.as_deref() == Some("ARCANE Hub")
+                    }
+                    _ => false,
+                })
+                .context("No hub found")?
+                .port_name
+                .clone()
+        }
+    };
+
+    serialport::new(&port_name, baud)
+        .timeout(Duration::from_millis(10))
+        .open()
+        .context("Failed to open serial port")
+}
+
+/// Prints every available serial port.
+fn list_ports() -> Result<()> {
+    let ports = serialport::available_ports().context("No ports found!")?;
 
-        let output = "This is a test".as_bytes();
-        port.write(output).context("Write failed!")?;
+    for p in ports {
+        println!("{}", p.port_name);
+    }
 
-        thread::sleep(Duration::from_millis(20));
+    Ok(())
+}
 
-        let mut serial_buf: Vec<u8> = vec![0; 32];
-        port.read(serial_buf.as_mut_slice())
-            .context("Found no data!")
-            .ok();
+/// Sends a single write command and exits with a status reflecting
+/// whether it succeeded.
+fn run_one_shot_write(
+    port_name: Option<&str>,
+    baud: u32,
+    format: OutputFormat,
+    hooks: &Hooks,
+    node_id: u8,
+    param_index: u8,
+    param_value: u64,
+) -> Result<()> {
+    let mut port = open_hub_port(port_name, baud)?;
+    let command = format!("write {} {} {}", node_id, param_index, param_value);
 
-        thread::sleep(Duration::from_millis(20));
+    match parse_write(&command).and_then(|data| {
+        port.write(&data).context("Write failed!")?;
+        Ok(data)
+    }) {
+        Ok(data) => {
+            output::print_write(format, node_id, param_index, param_value, &data);
+            hooks.on_write_sent(node_id, param_index, param_value, &data);
+            exit(0);
+        }
+        Err(e) => {
+            output::print_error(format, &e.to_string());
+            hooks.on_error(&e.to_string());
+            exit(1);
+        }
+    }
+}
+
+/// Sends a single read command, blocks for the decoded response, and exits
+/// with a status reflecting whether it succeeded.
+fn run_one_shot_read(
+    port_name: Option<&str>,
+    baud: u32,
+    format: OutputFormat,
+    hooks: &Hooks,
+    node_id: u8,
+    param_index: u8,
+) -> Result<()> {
+    let mut port = open_hub_port(port_name, baud)?;
+    let command = format!("read {} {}", node_id, param_index);
+
+    let result = parse_read(&command).and_then(|data| {
+        port.write(&data).context("Write failed!")?;
+        let frame = proto::read_frame(port.as_mut(), Duration::from_millis(200))?;
+        let reply = proto::decode_read_response(&frame, node_id, param_index)?;
+        Ok((reply, frame))
+    });
+
+    match result {
+        Ok((reply, frame)) => {
+            output::print_read(format, &reply, &frame);
+            exit(0);
+        }
+        Err(e) => {
+            output::print_error(format, &e.to_string());
+            hooks.on_error(&e.to_string());
+            exit(1);
+        }
     }
 }
 
@@ -96,7 +376,7 @@ fn main() -> Result<()> {
 /// let result = parse_write(command).unwrap();
 /// assert_eq!(result, vec![0x01, 0x01, 0x01, 0x02, 0x02, 0x37, 0x00, 0x00, 0x00, 0x00, 0x00]);
 /// ```
-fn parse_write(command: &str) -> Result<Vec<u8>> {
+pub(crate) fn parse_write(command: &str) -> Result<Vec<u8>> {
     let parts: Vec<&str> = command.split_whitespace().collect();
     if parts.len() == 4 {
         if let (Ok(node_id), Ok(param_index), Ok(param_value)) = (
@@ -104,11 +384,13 @@ fn parse_write(command: &str) -> Result<Vec<u8>> {
             parts[2].parse::<u8>(),
             parts[3].parse::<u64>(),
         ) {
-            // 0x07 corresponds to CFGW message
-            let mut data = vec![0x07, node_id, param_index];
+            let mut data = vec![proto::CFGW, node_id, param_index];
 
-            let mut value_bytes = param_value.to_be_bytes().to_vec();
-            value_bytes.retain(|&x| x != 0); // Remove leading zeros
+            let all_bytes = param_value.to_be_bytes();
+            let value_bytes = match all_bytes.iter().position(|&b| b != 0) {
+                Some(first_nonzero) => all_bytes[first_nonzero..].to_vec(),
+                None => vec![0], // param_value is 0: encode as a single zero byte
+            };
 
             let payload_length = value_bytes.len() as u8;
             if payload_length > 7 {
@@ -124,13 +406,46 @@ fn parse_write(command: &str) -> Result<Vec<u8>> {
                 data.push(0x00); // Pad with zeros
             }
 
-            return Ok(data);
+            Ok(data)
         } else {
-            return Err(anyhow::anyhow!(
+            Err(anyhow::anyhow!(
                 "Invalid node_id, param_index, or param_value"
-            ));
+            ))
         }
     } else {
-        return Err(anyhow::anyhow!("Invalid command format"));
+        Err(anyhow::anyhow!("Invalid command format"))
     }
 }
+
+/// Parses a `read <node_id> <param_index>` command into the CFGR request
+/// frame the hub expects.
+///
+/// A read request carries no payload, so the length byte is always zero
+/// and the rest of the 11-byte frame is padding.
+///
+/// # Example
+///
+/// ```
+/// let command = "read 1 1";
+/// let result = parse_read(command).unwrap();
+/// assert_eq!(result, vec![0x08, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+/// ```
+pub(crate) fn parse_read(command: &str) -> Result<Vec<u8>> {
+    let parts: Vec<&str> = command.split_whitespace().collect();
+    if parts.len() == 3 {
+        if let (Ok(node_id), Ok(param_index)) = (parts[1].parse::<u8>(), parts[2].parse::<u8>()) {
+            let mut data = vec![proto::CFGR, node_id, param_index, 0x00];
+
+            while data.len() < proto::FRAME_LEN {
+                data.push(0x00); // Pad with zeros
+            }
+
+            Ok(data)
+        } else {
+            Err(anyhow::anyhow!("Invalid node_id or param_index"))
+        }
+    } else {
+        Err(anyhow::anyhow!("Invalid command format"))
+    }
+}
+
@@ -0,0 +1,96 @@
+//! Output formatting for the console and one-shot commands: the default
+//! human-readable lines, or machine-readable JSON via `--format json` so
+//! `arcane` can be piped into other tools.
+
+use clap::ValueEnum;
+use console::style;
+
+use crate::proto::CfgRead;
+
+/// Selects how command results are printed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Human,
+    Json,
+}
+
+/// Renders a frame as comma-separated hex bytes, e.g. `"0x07,0x01,..."`.
+fn hex_frame(frame: &[u8]) -> String {
+    frame
+        .iter()
+        .map(|b| format!("{:#04x}", b))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Prints the result of a write command.
+pub fn print_write(format: OutputFormat, node_id: u8, param_index: u8, value: u64, frame: &[u8]) {
+    match format {
+        OutputFormat::Human => println!("{:#04x?}", frame),
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::json!({
+                "op": "write",
+                "node_id": node_id,
+                "param_index": param_index,
+                "value": value,
+                "frame": hex_frame(frame),
+            })
+        ),
+    }
+}
+
+/// Prints a decoded read response.
+pub fn print_read(format: OutputFormat, reply: &CfgRead, frame: &[u8]) {
+    match format {
+        OutputFormat::Human => println!("{:#?}", reply),
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::json!({
+                "op": "read",
+                "node_id": reply.node_id,
+                "param_index": reply.param_index,
+                "value": reply.value,
+                "frame": hex_frame(frame),
+            })
+        ),
+    }
+}
+
+/// Formats an unsolicited notification from the hub as a single line of
+/// text, with no trailing newline. Returned as a `String` rather than
+/// printed directly so it can be handed to rustyline's external printer,
+/// which needs to interleave it with the REPL's own redraws.
+pub fn format_notification(format: OutputFormat, notification: &CfgRead) -> String {
+    match format {
+        OutputFormat::Human => format!(
+            "{} node {} param {} -> {}",
+            style("notify").yellow().bold(),
+            notification.node_id,
+            notification.param_index,
+            notification.value
+        ),
+        OutputFormat::Json => serde_json::json!({
+            "op": "notify",
+            "node_id": notification.node_id,
+            "param_index": notification.param_index,
+            "value": notification.value,
+        })
+        .to_string(),
+    }
+}
+
+/// Prints an error to stderr, either as a plain `Error: ...` line or a JSON
+/// object with a `message` field.
+pub fn print_error(format: OutputFormat, message: &str) {
+    match format {
+        OutputFormat::Human => eprintln!("Error: {}", message),
+        OutputFormat::Json => eprintln!(
+            "{}",
+            serde_json::json!({
+                "op": "error",
+                "message": message,
+            })
+        ),
+    }
+}
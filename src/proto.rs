@@ -0,0 +1,348 @@
+//! Binary wire format helpers for ARCANE hub frames.
+//!
+//! Every request/response frame is a fixed 11 bytes: `[function_code,
+//! node_id, param_index, param_length, ...param_data, ...padding]`. This
+//! module provides a small `Cursor`-style reader for pulling fields back out
+//! of a raw frame without manual bounds checking.
+
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+
+/// Function code for a configuration write request.
+pub const CFGW: u8 = 0x07;
+/// Function code for a configuration read request (and its response).
+pub const CFGR: u8 = 0x08;
+/// Function code for an unsolicited notification (e.g. a parameter changed
+/// on the node).
+pub const NOTIFY: u8 = 0x09;
+
+/// Fixed width of every ARCANE configuration frame.
+pub const FRAME_LEN: usize = 11;
+
+/// A decoded response to a configuration-read request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CfgRead {
+    pub node_id: u8,
+    pub param_index: u8,
+    pub value: u64,
+}
+
+/// A `Cursor`-style reader over a frame buffer, returning `Result`s so
+/// callers can use `?` instead of manual bounds checks.
+struct Cursor<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        let byte = *self.buf.get(self.pos).context("unexpected end of frame")?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    /// Reads `len` bytes and reconstructs them as a big-endian integer.
+    fn read_be_bytes(&mut self, len: usize) -> Result<u64> {
+        if len > 7 {
+            bail!("param_length {} exceeds maximum of 7 bytes", len);
+        }
+        let mut value: u64 = 0;
+        for _ in 0..len {
+            value = (value << 8) | self.read_u8()? as u64;
+        }
+        Ok(value)
+    }
+}
+
+/// Fields common to every decoded frame, regardless of which function code
+/// it carries.
+#[derive(Debug)]
+struct DecodedFrame {
+    function_code: u8,
+    node_id: u8,
+    param_index: u8,
+    value: u64,
+}
+
+fn decode_frame(frame: &[u8]) -> Result<DecodedFrame> {
+    let mut cursor = Cursor::new(frame);
+
+    let function_code = cursor.read_u8()?;
+    let node_id = cursor.read_u8()?;
+    let param_index = cursor.read_u8()?;
+    let param_length = cursor.read_u8()? as usize;
+
+    if param_length > 7 {
+        bail!("param_length {} exceeds maximum of 7 bytes", param_length);
+    }
+
+    let value = cursor.read_be_bytes(param_length)?;
+
+    Ok(DecodedFrame {
+        function_code,
+        node_id,
+        param_index,
+        value,
+    })
+}
+
+/// Blocks on `port`, accumulating bytes until a full frame has arrived or
+/// `timeout` elapses.
+pub fn read_frame(port: &mut dyn serialport::SerialPort, timeout: Duration) -> Result<Vec<u8>> {
+    accumulate_frame(port, timeout)
+}
+
+/// Accumulation loop behind `read_frame`, generic over `Read` so it can be
+/// exercised in tests without a real serial port.
+fn accumulate_frame<R: std::io::Read + ?Sized>(reader: &mut R, timeout: Duration) -> Result<Vec<u8>> {
+    let deadline = Instant::now() + timeout;
+    let mut buf = Vec::with_capacity(FRAME_LEN);
+
+    while buf.len() < FRAME_LEN {
+        if Instant::now() >= deadline {
+            bail!("timed out waiting for frame");
+        }
+
+        let mut chunk = vec![0u8; FRAME_LEN - buf.len()];
+        match reader.read(&mut chunk) {
+            Ok(0) => continue,
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+            Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => continue,
+            Err(e) => return Err(e).context("failed reading from serial port"),
+        }
+    }
+
+    Ok(buf)
+}
+
+/// Decodes a raw CFGR response frame into a `CfgRead`, rejecting frames
+/// whose function code or node/param id don't match the request they're
+/// supposed to be answering.
+pub fn decode_read_response(frame: &[u8], node_id: u8, param_index: u8) -> Result<CfgRead> {
+    let decoded = decode_frame(frame)?;
+
+    if decoded.function_code != CFGR {
+        bail!(
+            "unexpected function code {:#04x} in read response",
+            decoded.function_code
+        );
+    }
+    if decoded.node_id != node_id || decoded.param_index != param_index {
+        bail!(
+            "response for node {} param {} doesn't match request (node {}, param {})",
+            decoded.node_id,
+            decoded.param_index,
+            node_id,
+            param_index
+        );
+    }
+
+    Ok(CfgRead {
+        node_id: decoded.node_id,
+        param_index: decoded.param_index,
+        value: decoded.value,
+    })
+}
+
+/// Decodes a raw NOTIFY frame into a `CfgRead`-shaped payload. Unlike
+/// `decode_read_response`, there is no outstanding request to match
+/// against: the hub sends these unprompted whenever a parameter changes.
+pub fn decode_notification(frame: &[u8]) -> Result<CfgRead> {
+    let decoded = decode_frame(frame)?;
+
+    if decoded.function_code != NOTIFY {
+        bail!(
+            "unexpected function code {:#04x} in notification",
+            decoded.function_code
+        );
+    }
+
+    Ok(CfgRead {
+        node_id: decoded.node_id,
+        param_index: decoded.param_index,
+        value: decoded.value,
+    })
+}
+
+/// Runs for the lifetime of the caller on a background thread, continuously
+/// draining `port` and decoding frames as they arrive: CFGR responses are
+/// forwarded to `response_tx` for a waiting request to pick up (see
+/// `recv_matching_response`), unsolicited NOTIFY frames are decoded and
+/// forwarded to `notify_tx`. Shared by the REPL and the TCP bridge so both
+/// demux the hub's unsolicited notifications instead of reading raw bytes
+/// directly off the shared port.
+pub fn demux_frames(
+    mut port: Box<dyn serialport::SerialPort>,
+    response_tx: mpsc::Sender<Vec<u8>>,
+    notify_tx: mpsc::Sender<(CfgRead, Vec<u8>)>,
+) {
+    let mut buf = Vec::with_capacity(FRAME_LEN);
+
+    loop {
+        let mut chunk = vec![0u8; FRAME_LEN - buf.len()];
+        match port.read(&mut chunk) {
+            Ok(0) => continue,
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+            Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => continue,
+            Err(_) => return,
+        }
+
+        if buf.len() < FRAME_LEN {
+            continue;
+        }
+
+        let frame = std::mem::replace(&mut buf, Vec::with_capacity(FRAME_LEN));
+
+        // Note: `response_tx.send(frame)` can't be hoisted into the arm's
+        // match guard (clippy::collapsible_match) -- it moves `frame`, which
+        // the NOTIFY arm below also needs.
+        #[allow(clippy::collapsible_match)]
+        match frame.first() {
+            Some(&CFGR) => {
+                if response_tx.send(frame).is_err() {
+                    return;
+                }
+            }
+            Some(&NOTIFY) => {
+                if let Ok(notification) = decode_notification(&frame) {
+                    if notify_tx.send((notification, frame)).is_err() {
+                        return;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Waits for `demux_frames` to hand back a response frame matching
+/// `node_id`/`param_index` on `response_rx`, decoding (and discarding)
+/// anything stale or mismatched in between, until `timeout` elapses.
+pub fn recv_matching_response(
+    response_rx: &mpsc::Receiver<Vec<u8>>,
+    node_id: u8,
+    param_index: u8,
+    timeout: Duration,
+) -> Result<CfgRead> {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            bail!("timed out waiting for read response");
+        }
+
+        let frame = response_rx
+            .recv_timeout(remaining)
+            .map_err(|_| anyhow::anyhow!("timed out waiting for read response"))?;
+
+        match decode_read_response(&frame, node_id, param_index) {
+            Ok(reply) => return Ok(reply),
+            Err(_) => continue,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    /// A `Read` that hands back `chunks` one at a time, in order, then
+    /// behaves like a real serial port with no more data to offer: every
+    /// further call times out. This lets tests exercise partial/short reads
+    /// without a real serial port.
+    struct ChunkedReader {
+        chunks: std::collections::VecDeque<Vec<u8>>,
+    }
+
+    impl io::Read for ChunkedReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.chunks.pop_front() {
+                Some(chunk) => {
+                    buf[..chunk.len()].copy_from_slice(&chunk);
+                    Ok(chunk.len())
+                }
+                None => Err(io::Error::new(io::ErrorKind::TimedOut, "no more data")),
+            }
+        }
+    }
+
+    fn cfgr_frame(node_id: u8, param_index: u8, value: u64) -> Vec<u8> {
+        let mut frame = vec![CFGR, node_id, param_index, 1, value as u8];
+        frame.resize(FRAME_LEN, 0);
+        frame
+    }
+
+    #[test]
+    fn accumulate_frame_reassembles_short_reads() {
+        let full = cfgr_frame(1, 2, 42);
+        let mut reader = ChunkedReader {
+            chunks: vec![full[..3].to_vec(), full[3..7].to_vec(), full[7..].to_vec()].into(),
+        };
+
+        let frame = accumulate_frame(&mut reader, Duration::from_millis(50)).unwrap();
+        assert_eq!(frame, full);
+    }
+
+    #[test]
+    fn accumulate_frame_times_out_on_partial_frame() {
+        let mut reader = ChunkedReader {
+            chunks: vec![vec![CFGR, 1, 2]].into(),
+        };
+
+        let err = accumulate_frame(&mut reader, Duration::from_millis(20)).unwrap_err();
+        assert!(err.to_string().contains("timed out"));
+    }
+
+    #[test]
+    fn decode_read_response_rejects_wrong_function_code() {
+        let frame = {
+            let mut f = cfgr_frame(1, 2, 42);
+            f[0] = NOTIFY;
+            f
+        };
+
+        let err = decode_read_response(&frame, 1, 2).unwrap_err();
+        assert!(err.to_string().contains("unexpected function code"));
+    }
+
+    #[test]
+    fn decode_read_response_rejects_mismatched_node_or_param() {
+        let frame = cfgr_frame(1, 2, 42);
+
+        let err = decode_read_response(&frame, 1, 3).unwrap_err();
+        assert!(err.to_string().contains("doesn't match request"));
+    }
+
+    #[test]
+    fn decode_frame_rejects_param_length_over_seven() {
+        let mut frame = vec![CFGR, 1, 2, 8, 0, 0, 0, 0, 0, 0, 0];
+        frame.resize(FRAME_LEN, 0);
+
+        let err = decode_frame(&frame).unwrap_err();
+        assert!(err.to_string().contains("exceeds maximum of 7 bytes"));
+    }
+
+    /// Values whose big-endian encoding contains a non-leading zero byte
+    /// (e.g. 256 = 0x0100, 65537 = 0x010001) regressed a prior `retain(|&x|
+    /// x != 0)` bug that stripped every zero byte, not just leading ones.
+    #[test]
+    fn parse_write_round_trips_values_with_interior_zero_bytes() {
+        for value in [256u64, 65537] {
+            let command = format!("write 1 2 {}", value);
+            let frame = crate::parse_write(&command).unwrap();
+            let decoded = decode_frame(&frame).unwrap();
+            assert_eq!(decoded.value, value);
+            assert_eq!(decoded.function_code, CFGW);
+            assert_eq!(decoded.node_id, 1);
+            assert_eq!(decoded.param_index, 2);
+        }
+    }
+}
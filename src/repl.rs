@@ -0,0 +1,88 @@
+//! Interactive REPL support: tab completion and hinting for the ARCANE
+//! console, layered on top of `rustyline`.
+
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::{Hinter, HistoryHinter};
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+
+/// Verbs the console understands, offered as completions for the first
+/// word of a command.
+const VERBS: &[&str] = &["read", "write", "help", "quit"];
+
+/// Node ids known to respond on the bus, offered as completions for the
+/// second word of `read`/`write` commands.
+const NODE_IDS: &[&str] = &["1", "2", "3"];
+
+/// Parameter indices known to exist on a node, offered as completions for
+/// the third word of `read`/`write` commands.
+const PARAM_INDICES: &[&str] = &["1", "2", "3", "4"];
+
+/// Completion/hinting helper for the ARCANE REPL: completes verbs, node
+/// ids and parameter indices, and falls back to history-based hints.
+pub struct CmdHelper {
+    hinter: HistoryHinter,
+}
+
+impl CmdHelper {
+    pub fn new() -> Self {
+        Self {
+            hinter: HistoryHinter::new(),
+        }
+    }
+}
+
+impl Helper for CmdHelper {}
+
+impl Completer for CmdHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let (start, word) = current_word(line, pos);
+        let word_index = line[..start].split_whitespace().count();
+
+        let candidates: &[&str] = match word_index {
+            0 => VERBS,
+            1 => NODE_IDS,
+            2 => PARAM_INDICES,
+            _ => &[],
+        };
+
+        let matches = candidates
+            .iter()
+            .filter(|c| c.starts_with(word))
+            .map(|c| Pair {
+                display: c.to_string(),
+                replacement: c.to_string(),
+            })
+            .collect();
+
+        Ok((start, matches))
+    }
+}
+
+impl Hinter for CmdHelper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, ctx: &Context<'_>) -> Option<String> {
+        self.hinter.hint(line, pos, ctx)
+    }
+}
+
+impl Highlighter for CmdHelper {}
+impl Validator for CmdHelper {}
+
+/// Finds the start index and text of the word under the cursor.
+fn current_word(line: &str, pos: usize) -> (usize, &str) {
+    let start = line[..pos]
+        .rfind(char::is_whitespace)
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    (start, &line[start..pos])
+}
@@ -0,0 +1,124 @@
+//! Headless TCP bridge: lets other programs drive the hub concurrently over
+//! a line-based protocol, speaking the same `read`/`write` commands as the
+//! interactive console.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+
+use crate::{parse_read, parse_write, proto};
+
+/// Assigns each accepted connection a unique id for logging.
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// How long to wait for a read response before giving up on a single
+/// request.
+const RESPONSE_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// A write handle to the hub plus the channel `proto::demux_frames` delivers
+/// CFGR responses on. Guarded by a single `Mutex` so a connection's full
+/// write-then-wait-for-response round trip runs as one critical section,
+/// serializing concurrent clients onto the wire. `dispatch` never reads raw
+/// bytes itself, so a NOTIFY frame arriving mid-round-trip can't be mistaken
+/// for the response.
+struct HubSession {
+    writer: Box<dyn serialport::SerialPort>,
+    response_rx: mpsc::Receiver<Vec<u8>>,
+}
+
+/// Runs the TCP bridge, blocking until the listener errors out.
+///
+/// A background thread (`proto::demux_frames`) continuously drains the
+/// serial port and demuxes CFGR responses from unsolicited NOTIFY frames,
+/// mirroring the REPL's background reader, so a notification is never taken
+/// for the response a request is waiting for.
+pub fn serve(listen_addr: &str, port: Box<dyn serialport::SerialPort>) -> Result<()> {
+    let listener = TcpListener::bind(listen_addr)
+        .with_context(|| format!("Failed to bind {}", listen_addr))?;
+
+    let reader_port = port
+        .try_clone()
+        .context("Failed to clone serial port for background reader")?;
+
+    let (response_tx, response_rx) = mpsc::channel::<Vec<u8>>();
+    let (notify_tx, notify_rx) = mpsc::channel::<(proto::CfgRead, Vec<u8>)>();
+
+    thread::spawn(move || proto::demux_frames(reader_port, response_tx, notify_tx));
+    thread::spawn(move || {
+        for (notification, _frame) in notify_rx {
+            println!(
+                "notify: node {} param {} -> {}",
+                notification.node_id, notification.param_index, notification.value
+            );
+        }
+    });
+
+    let session = Arc::new(Mutex::new(HubSession {
+        writer: port,
+        response_rx,
+    }));
+
+    println!("Listening on {}", listen_addr);
+
+    for stream in listener.incoming() {
+        let stream = stream.context("Failed to accept connection")?;
+        let session = Arc::clone(&session);
+        let connection_id = NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
+
+        thread::spawn(move || {
+            if let Err(e) = handle_connection(stream, &session) {
+                eprintln!("[conn {}] {}", connection_id, e);
+            }
+        });
+    }
+
+    Ok(())
+}
+
+fn handle_connection(stream: TcpStream, session: &Arc<Mutex<HubSession>>) -> Result<()> {
+    let mut writer = stream.try_clone().context("Failed to clone TCP stream")?;
+    let reader = BufReader::new(stream);
+
+    for line in reader.lines() {
+        let line = line.context("Failed to read from client")?;
+        let reply = match dispatch(&line, session) {
+            Ok(reply) => reply,
+            Err(e) => format!("error: {}", e),
+        };
+
+        writeln!(writer, "{}", reply).context("Failed to write to client")?;
+    }
+
+    Ok(())
+}
+
+/// Parses one line as a `read`/`write` command, sends it to the hub while
+/// holding the session lock for the full round trip, and returns the
+/// decoded reply as a single line of text.
+fn dispatch(line: &str, session: &Arc<Mutex<HubSession>>) -> Result<String> {
+    let command = line.trim();
+
+    let data = if command.starts_with("read") {
+        parse_read(command)?
+    } else if command.starts_with("write") {
+        parse_write(command)?
+    } else {
+        bail!("unknown command");
+    };
+
+    let mut session = session.lock().unwrap();
+    session.writer.write(&data).context("Write failed!")?;
+
+    if command.starts_with("read") {
+        let reply =
+            proto::recv_matching_response(&session.response_rx, data[1], data[2], RESPONSE_TIMEOUT)?;
+        Ok(format!("{:?}", reply))
+    } else {
+        Ok("ok".to_string())
+    }
+}